@@ -17,7 +17,8 @@ use super::priority_queue::PriorityQueue;
 use super::mem::transmute;
 use super::thread::{Mutex, Cond};
 use super::ops::Ord;
-use super::option::Option;
+use super::option::{Option, Some, None};
+use super::result::{Result, Ok, Err};
 
 trait GenericQueue<T> {
     fn generic_push(&mut self, item: T);
@@ -41,7 +42,8 @@ impl<T: Ord> GenericQueue<T> for PriorityQueue<T> {
 struct QueueBox<T> {
     queue: T,
     mutex: Mutex,
-    not_empty: Cond
+    not_empty: Cond,
+    closed: bool
 }
 
 struct QueuePtr<T> {
@@ -51,7 +53,8 @@ struct QueuePtr<T> {
 impl<A, T: GenericQueue<A>> QueuePtr<T> {
     fn new(queue: T) -> QueuePtr<T> {
         unsafe {
-            let box = QueueBox { queue: queue, mutex: Mutex::new(), not_empty: Cond::new() };
+            let box = QueueBox { queue: queue, mutex: Mutex::new(), not_empty: Cond::new(),
+                                  closed: false };
             QueuePtr { ptr: Arc::new_unchecked(box) }
         }
     }
@@ -67,13 +70,74 @@ impl<A, T: GenericQueue<A>> QueuePtr<T> {
         }
     }
 
-    pub fn push(&self, item: A) {
+    /// Pop a value, returning `None` once the queue is closed and drained rather than blocking
+    /// forever.
+    fn pop_opt(&self) -> Option<A> {
+        unsafe {
+            let box: &mut QueueBox<T> = transmute(self.ptr.borrow());
+            let mut guard = box.mutex.lock_guard();
+            while box.queue.generic_len() == 0 {
+                if box.closed {
+                    return None
+                }
+                box.not_empty.wait_guard(&mut guard)
+            }
+            box.queue.generic_pop()
+        }
+    }
+
+    fn pop_timeout(&self, ms: u64) -> Option<A> {
+        unsafe {
+            let box: &mut QueueBox<T> = transmute(self.ptr.borrow());
+            let mut guard = box.mutex.lock_guard();
+            while box.queue.generic_len() == 0 {
+                if box.closed {
+                    return None
+                }
+                if box.not_empty.wait_guard_timeout(&mut guard, ms) {
+                    return None
+                }
+            }
+            box.queue.generic_pop()
+        }
+    }
+
+    /// Pop a value without blocking, returning `None` immediately if the queue is empty.
+    fn try_pop(&self) -> Option<A> {
         unsafe {
             let box: &mut QueueBox<T> = transmute(self.ptr.borrow());
             box.mutex.lock();
+            let item = box.queue.generic_pop();
+            box.mutex.unlock();
+            item
+        }
+    }
+
+    /// Push a value, returning `false` instead if the queue has been closed.
+    pub fn push(&self, item: A) -> bool {
+        unsafe {
+            let box: &mut QueueBox<T> = transmute(self.ptr.borrow());
+            box.mutex.lock();
+            if box.closed {
+                box.mutex.unlock();
+                return false
+            }
             box.queue.generic_push(item);
             box.mutex.unlock();
-            box.not_empty.signal()
+            box.not_empty.signal();
+            true
+        }
+    }
+
+    /// Mark the queue closed: subsequent `push`es are rejected, and blocked/future `pop_opt`s
+    /// return `None` once the queue has been drained.
+    fn close(&self) {
+        unsafe {
+            let box: &mut QueueBox<T> = transmute(self.ptr.borrow());
+            box.mutex.lock();
+            box.closed = true;
+            box.mutex.unlock();
+            box.not_empty.broadcast()
         }
     }
 }
@@ -100,10 +164,36 @@ impl<T> Queue<T> {
         self.ptr.pop()
     }
 
-    /// Push a value to the back of the queue
-    pub fn push(&self, item: T) {
+    /// Pop a value from the front of the queue, blocking until the queue is not empty. Returns
+    /// `None` once the queue has been `close`d and drained, instead of blocking forever.
+    pub fn pop_opt(&self) -> Option<T> {
+        self.ptr.pop_opt()
+    }
+
+    /// Pop a value from the front of the queue, blocking until the queue is not empty or `ms`
+    /// milliseconds have elapsed, in which case `None` is returned.
+    pub fn pop_timeout(&self, ms: u64) -> Option<T> {
+        self.ptr.pop_timeout(ms)
+    }
+
+    /// Pop a value from the front of the queue without blocking, returning `None` immediately if
+    /// the queue is empty.
+    pub fn try_pop(&self) -> Option<T> {
+        self.ptr.try_pop()
+    }
+
+    /// Push a value to the back of the queue, returning `false` instead if the queue has been
+    /// `close`d.
+    pub fn push(&self, item: T) -> bool {
         self.ptr.push(item)
     }
+
+    /// Close the queue: subsequent `push`es are rejected, and `pop_opt` returns `None` once the
+    /// queue has been drained rather than blocking forever. Lets producers signal "no more items"
+    /// without consumers having to agree on a sentinel value.
+    pub fn close(&self) {
+        self.ptr.close()
+    }
 }
 
 impl<T> Clone for Queue<T> {
@@ -129,10 +219,34 @@ impl<T: Ord> BlockingPriorityQueue<T> {
         self.ptr.pop()
     }
 
-    /// Push a value into the queue
-    pub fn push(&self, item: T) {
+    /// Pop the largest value from the queue, blocking until the queue is not empty. Returns
+    /// `None` once the queue has been `close`d and drained, instead of blocking forever.
+    pub fn pop_opt(&self) -> Option<T> {
+        self.ptr.pop_opt()
+    }
+
+    /// Pop the largest value from the queue, blocking until the queue is not empty or `ms`
+    /// milliseconds have elapsed, in which case `None` is returned.
+    pub fn pop_timeout(&self, ms: u64) -> Option<T> {
+        self.ptr.pop_timeout(ms)
+    }
+
+    /// Pop the largest value from the queue without blocking, returning `None` immediately if the
+    /// queue is empty.
+    pub fn try_pop(&self) -> Option<T> {
+        self.ptr.try_pop()
+    }
+
+    /// Push a value into the queue, returning `false` instead if the queue has been `close`d.
+    pub fn push(&self, item: T) -> bool {
         self.ptr.push(item)
     }
+
+    /// Close the queue: subsequent `push`es are rejected, and `pop_opt` returns `None` once the
+    /// queue has been drained rather than blocking forever.
+    pub fn close(&self) {
+        self.ptr.close()
+    }
 }
 
 impl<T> Clone for BlockingPriorityQueue<T> {
@@ -148,7 +262,8 @@ struct BoundedQueueBox<T> {
     mutex: Mutex,
     not_empty: Cond,
     not_full: Cond,
-    maximum: uint
+    maximum: uint,
+    closed: bool
 }
 
 struct BoundedQueuePtr<T> {
@@ -159,7 +274,7 @@ impl<A, T: GenericQueue<A>> BoundedQueuePtr<T> {
     pub fn new(maximum: uint, queue: T) -> BoundedQueuePtr<T> {
         unsafe {
             let box = BoundedQueueBox { deque: queue, mutex: Mutex::new(), not_empty: Cond::new(),
-                                        not_full: Cond::new(), maximum: maximum };
+                                        not_full: Cond::new(), maximum: maximum, closed: false };
             BoundedQueuePtr { ptr: Arc::new_unchecked(box) }
         }
     }
@@ -178,16 +293,109 @@ impl<A, T: GenericQueue<A>> BoundedQueuePtr<T> {
         }
     }
 
-    pub fn push(&self, item: A) {
+    /// Pop a value, returning `None` once the queue is closed and drained rather than blocking
+    /// forever.
+    pub fn pop_opt(&self) -> Option<A> {
+        unsafe {
+            let box: &mut BoundedQueueBox<T> = transmute(self.ptr.borrow());
+            box.mutex.lock();
+            while box.deque.generic_len() == 0 {
+                if box.closed {
+                    box.mutex.unlock();
+                    return None
+                }
+                box.not_empty.wait(&mut box.mutex)
+            }
+            let item = box.deque.generic_pop();
+            box.mutex.unlock();
+            box.not_full.signal();
+            item
+        }
+    }
+
+    pub fn pop_timeout(&self, ms: u64) -> Option<A> {
+        unsafe {
+            let box: &mut BoundedQueueBox<T> = transmute(self.ptr.borrow());
+            box.mutex.lock();
+            while box.deque.generic_len() == 0 {
+                if box.closed {
+                    box.mutex.unlock();
+                    return None
+                }
+                if box.not_empty.wait_timeout(&mut box.mutex, ms) {
+                    box.mutex.unlock();
+                    return None
+                }
+            }
+            let item = box.deque.generic_pop();
+            box.mutex.unlock();
+            box.not_full.signal();
+            item
+        }
+    }
+
+    /// Pop a value without blocking, returning `None` immediately if the queue is empty.
+    pub fn try_pop(&self) -> Option<A> {
+        unsafe {
+            let box: &mut BoundedQueueBox<T> = transmute(self.ptr.borrow());
+            box.mutex.lock();
+            if box.deque.generic_len() == 0 {
+                box.mutex.unlock();
+                return None
+            }
+            let item = box.deque.generic_pop();
+            box.mutex.unlock();
+            box.not_full.signal();
+            item
+        }
+    }
+
+    /// Push a value, returning `false` instead if the queue has been closed.
+    pub fn push(&self, item: A) -> bool {
         unsafe {
             let box: &mut BoundedQueueBox<T> = transmute(self.ptr.borrow());
             box.mutex.lock();
-            while box.deque.generic_len() == box.maximum {
+            while !box.closed && box.deque.generic_len() == box.maximum {
                 box.not_full.wait(&mut box.mutex)
             }
+            if box.closed {
+                box.mutex.unlock();
+                return false
+            }
+            box.deque.generic_push(item);
+            box.mutex.unlock();
+            box.not_empty.signal();
+            true
+        }
+    }
+
+    /// Push a value without blocking, returning the item back as `Err` if the queue is full or
+    /// closed.
+    pub fn try_push(&self, item: A) -> Result<(), A> {
+        unsafe {
+            let box: &mut BoundedQueueBox<T> = transmute(self.ptr.borrow());
+            box.mutex.lock();
+            if box.closed || box.deque.generic_len() == box.maximum {
+                box.mutex.unlock();
+                return Err(item)
+            }
             box.deque.generic_push(item);
             box.mutex.unlock();
-            box.not_empty.signal()
+            box.not_empty.signal();
+            Ok(())
+        }
+    }
+
+    /// Mark the queue closed: subsequent `push`es are rejected, and blocked/future `pop_opt`s
+    /// return `None` once the queue has been drained.
+    pub fn close(&self) {
+        unsafe {
+            let box: &mut BoundedQueueBox<T> = transmute(self.ptr.borrow());
+            box.mutex.lock();
+            box.closed = true;
+            box.mutex.unlock();
+            box.not_empty.broadcast();
+            box.not_full.broadcast()
         }
     }
 }
@@ -214,10 +422,42 @@ impl<T> BoundedQueue<T> {
         self.ptr.pop()
     }
 
-    /// Push a value to the back of the queue, blocking until the queue is not full
-    pub fn push(&self, item: T) {
+    /// Pop a value from the front of the queue, blocking until the queue is not empty. Returns
+    /// `None` once the queue has been `close`d and drained, instead of blocking forever.
+    pub fn pop_opt(&self) -> Option<T> {
+        self.ptr.pop_opt()
+    }
+
+    /// Pop a value from the front of the queue, blocking until the queue is not empty or `ms`
+    /// milliseconds have elapsed, in which case `None` is returned.
+    pub fn pop_timeout(&self, ms: u64) -> Option<T> {
+        self.ptr.pop_timeout(ms)
+    }
+
+    /// Pop a value from the front of the queue without blocking, returning `None` immediately if
+    /// the queue is empty.
+    pub fn try_pop(&self) -> Option<T> {
+        self.ptr.try_pop()
+    }
+
+    /// Push a value to the back of the queue, blocking until the queue is not full. Returns
+    /// `false` instead if the queue has been `close`d.
+    pub fn push(&self, item: T) -> bool {
         self.ptr.push(item)
     }
+
+    /// Push a value to the back of the queue without blocking, returning the item back as `Err`
+    /// if the queue is full or has been `close`d.
+    pub fn try_push(&self, item: T) -> Result<(), T> {
+        self.ptr.try_push(item)
+    }
+
+    /// Close the queue: subsequent `push`es are rejected, and `pop_opt` returns `None` once the
+    /// queue has been drained rather than blocking forever. Lets producers signal "no more items"
+    /// without consumers having to agree on a sentinel value.
+    pub fn close(&self) {
+        self.ptr.close()
+    }
 }
 
 impl<T> Clone for BoundedQueue<T> {
@@ -243,10 +483,41 @@ impl<T: Ord> BoundedPriorityQueue<T> {
         self.ptr.pop()
     }
 
-    /// Push a value into the queue, blocking until the queue is not full
-    pub fn push(&self, item: T) {
+    /// Pop a value from the front of the queue, blocking until the queue is not empty. Returns
+    /// `None` once the queue has been `close`d and drained, instead of blocking forever.
+    pub fn pop_opt(&self) -> Option<T> {
+        self.ptr.pop_opt()
+    }
+
+    /// Pop a value from the front of the queue, blocking until the queue is not empty or `ms`
+    /// milliseconds have elapsed, in which case `None` is returned.
+    pub fn pop_timeout(&self, ms: u64) -> Option<T> {
+        self.ptr.pop_timeout(ms)
+    }
+
+    /// Pop a value from the front of the queue without blocking, returning `None` immediately if
+    /// the queue is empty.
+    pub fn try_pop(&self) -> Option<T> {
+        self.ptr.try_pop()
+    }
+
+    /// Push a value into the queue, blocking until the queue is not full. Returns `false` instead
+    /// if the queue has been `close`d.
+    pub fn push(&self, item: T) -> bool {
         self.ptr.push(item)
     }
+
+    /// Push a value into the queue without blocking, returning the item back as `Err` if the
+    /// queue is full or has been `close`d.
+    pub fn try_push(&self, item: T) -> Result<(), T> {
+        self.ptr.try_push(item)
+    }
+
+    /// Close the queue: subsequent `push`es are rejected, and `pop_opt` returns `None` once the
+    /// queue has been drained rather than blocking forever.
+    pub fn close(&self) {
+        self.ptr.close()
+    }
 }
 
 impl<T> Clone for BoundedPriorityQueue<T> {
@@ -48,3 +48,16 @@ pub struct pthread_cond_t {
 pub struct pthread_cond_attr_t {
     priv size: u32
 }
+
+pub struct pthread_rwlock_t {
+    priv size: [u64, ..8]
+}
+
+pub struct pthread_rwlock_attr_t {
+    priv size: u64
+}
+
+pub struct timespec {
+    tv_sec: i64,
+    tv_nsec: i64
+}
@@ -11,21 +11,25 @@
 use super::container::Container;
 use super::c_types::{c_int, pthread_t, pthread_attr_t, pthread_mutex_t, pthread_mutex_attr_t};
 use super::c_types::{pthread_cond_t, pthread_cond_attr_t};
+use super::c_types::{pthread_rwlock_t, pthread_rwlock_attr_t};
+use super::c_types::timespec;
 use super::fail::{abort, assert};
 use super::ops::Drop;
 use super::kinds::Send;
-use super::mem::{forget, uninit, transmute};
+use super::mem::{forget, uninit, transmute, replace};
 use super::concurrent::Queue;
 use super::vec::Vec;
 use super::heap::Heap;
 use super::option::{Option, Some, None};
 use super::clone::Clone;
+use super::arc::Arc;
 
 extern {
     fn pthread_create(thread: *mut pthread_t, attr: *pthread_attr_t,
                       start_routine: extern "C" fn(*mut u8) -> *mut u8,
                       arg: *mut u8) -> c_int;
     fn pthread_join(thread: pthread_t, retval: *mut *mut u8) -> c_int;
+    fn pthread_detach(thread: pthread_t) -> c_int;
 
     fn sched_yield() -> c_int;
 
@@ -36,6 +40,7 @@ extern {
     fn pthread_mutexattr_init(attr: *mut pthread_mutex_attr_t) -> c_int;
     fn pthread_mutexattr_destroy(attr: *mut pthread_mutex_attr_t) -> c_int;
     fn pthread_mutexattr_settype(attr: *mut pthread_mutex_attr_t, ty: c_int) -> c_int;
+    fn pthread_mutexattr_setpshared(attr: *mut pthread_mutex_attr_t, pshared: c_int) -> c_int;
 
     fn pthread_mutex_init(mutex: *mut pthread_mutex_t, attr: *pthread_mutex_attr_t) -> c_int;
     fn pthread_mutex_destroy(mutex: *mut pthread_mutex_t) -> c_int;
@@ -43,16 +48,38 @@ extern {
     fn pthread_mutex_trylock(mutex: *mut pthread_mutex_t) -> c_int;
     fn pthread_mutex_unlock(mutex: *mut pthread_mutex_t) -> c_int;
 
+    fn pthread_condattr_init(attr: *mut pthread_cond_attr_t) -> c_int;
+    fn pthread_condattr_destroy(attr: *mut pthread_cond_attr_t) -> c_int;
+    fn pthread_condattr_setpshared(attr: *mut pthread_cond_attr_t, pshared: c_int) -> c_int;
+
     fn pthread_cond_init(cond: *mut pthread_cond_t, attr: *pthread_cond_attr_t) -> c_int;
     fn pthread_cond_destroy(cond: *mut pthread_cond_t) -> c_int;
     fn pthread_cond_signal(cond: *mut pthread_cond_t) -> c_int;
     fn pthread_cond_broadcast(cond: *mut pthread_cond_t) -> c_int;
     fn pthread_cond_wait(cond: *mut pthread_cond_t, mutex: *mut pthread_mutex_t) -> c_int;
+
+    fn pthread_rwlock_init(rwlock: *mut pthread_rwlock_t, attr: *pthread_rwlock_attr_t) -> c_int;
+    fn pthread_rwlock_destroy(rwlock: *mut pthread_rwlock_t) -> c_int;
+    fn pthread_rwlock_rdlock(rwlock: *mut pthread_rwlock_t) -> c_int;
+    fn pthread_rwlock_wrlock(rwlock: *mut pthread_rwlock_t) -> c_int;
+    fn pthread_rwlock_tryrdlock(rwlock: *mut pthread_rwlock_t) -> c_int;
+    fn pthread_rwlock_trywrlock(rwlock: *mut pthread_rwlock_t) -> c_int;
+    fn pthread_rwlock_unlock(rwlock: *mut pthread_rwlock_t) -> c_int;
+
+    fn pthread_cond_timedwait(cond: *mut pthread_cond_t, mutex: *mut pthread_mutex_t,
+                              abstime: *timespec) -> c_int;
+
+    fn clock_gettime(clock_id: c_int, tp: *mut timespec) -> c_int;
 }
 
 static PTHREAD_CREATE_DETACHED: c_int = 1;
+static PTHREAD_MUTEX_RECURSIVE: c_int = 1;
 static PTHREAD_MUTEX_ERRORCHECK: c_int = 2;
 static EBUSY: c_int = 16;
+static ETIMEDOUT: c_int = 110;
+static CLOCK_REALTIME: c_int = 0;
+static NSEC_PER_SEC: i64 = 1_000_000_000;
+static PTHREAD_PROCESS_SHARED: c_int = 1;
 
 /// An owned thread type, joined in the destructor.
 pub struct Thread<A> {
@@ -114,6 +141,17 @@ impl<A: Send> Thread<A> {
             transmute(result)
         }
     }
+
+    /// Detach the thread, consuming the handle so the destructor no longer blocks waiting to
+    /// join it. The worker keeps running independently and its result is discarded; as with
+    /// `spawn_detached`, if `main` returns the program exits immediately even if this thread is
+    /// still running.
+    pub fn detach(self) {
+        unsafe {
+            assert(pthread_detach(self.thread) == 0);
+            forget(self)
+        }
+    }
 }
 
 #[unsafe_destructor]
@@ -167,6 +205,42 @@ impl Mutex {
         }
     }
 
+    /// Return a new `Mutex` configured to coordinate across processes sharing the memory mapping
+    /// it's placed in, rather than being private to the creating process. Note that this
+    /// constructs the mutex on the stack before it's moved into place; prefer `init_at` when the
+    /// destination is already a shared mapping (e.g. returned by `mmap`).
+    pub fn new_shared() -> Mutex {
+        unsafe {
+            let mut attr = uninit();
+            if pthread_mutexattr_init(&mut attr) != 0 {
+                abort()
+            }
+            assert(pthread_mutexattr_setpshared(&mut attr, PTHREAD_PROCESS_SHARED) == 0);
+            let mut mutex = uninit();
+            if pthread_mutex_init(&mut mutex, &attr) != 0 {
+                abort()
+            }
+            assert(pthread_mutexattr_destroy(&mut attr) == 0);
+            Mutex { mutex: mutex }
+        }
+    }
+
+    /// Construct a process-shared `Mutex` directly at `ptr`, rather than building one and moving
+    /// it there. Intended for a location within memory already shared across processes (e.g. an
+    /// `mmap`ed region), so that no process ever runs `Drop` on a copy of it; the caller must
+    /// ensure `Drop` only runs once, in exactly one of the sharing processes.
+    pub unsafe fn init_at(ptr: *mut Mutex) {
+        let mut attr = uninit();
+        if pthread_mutexattr_init(&mut attr) != 0 {
+            abort()
+        }
+        assert(pthread_mutexattr_setpshared(&mut attr, PTHREAD_PROCESS_SHARED) == 0);
+        if pthread_mutex_init(&mut (*ptr).mutex, &attr) != 0 {
+            abort()
+        }
+        assert(pthread_mutexattr_destroy(&mut attr) == 0);
+    }
+
     /// Grab ownership of the mutex.
     pub unsafe fn lock(&mut self) {
         assert(pthread_mutex_lock(&mut self.mutex) == 0)
@@ -219,6 +293,41 @@ impl Cond {
         }
     }
 
+    /// Return a new `Cond` configured to coordinate across processes sharing the memory mapping
+    /// it's placed in, rather than being private to the creating process. As with
+    /// `Mutex::new_shared`, this constructs the condition variable on the stack before it's moved
+    /// into place; prefer `init_at` when the destination is already a shared mapping.
+    pub fn new_shared() -> Cond {
+        unsafe {
+            let mut attr = uninit();
+            if pthread_condattr_init(&mut attr) != 0 {
+                abort()
+            }
+            assert(pthread_condattr_setpshared(&mut attr, PTHREAD_PROCESS_SHARED) == 0);
+            let mut cond = uninit();
+            if pthread_cond_init(&mut cond, &attr) != 0 {
+                abort()
+            }
+            assert(pthread_condattr_destroy(&mut attr) == 0);
+            Cond { cond: cond }
+        }
+    }
+
+    /// Construct a process-shared `Cond` directly at `ptr`, rather than building one and moving
+    /// it there. As with `Mutex::init_at`, the caller must ensure `Drop` only runs once, in
+    /// exactly one of the sharing processes.
+    pub unsafe fn init_at(ptr: *mut Cond) {
+        let mut attr = uninit();
+        if pthread_condattr_init(&mut attr) != 0 {
+            abort()
+        }
+        assert(pthread_condattr_setpshared(&mut attr, PTHREAD_PROCESS_SHARED) == 0);
+        if pthread_cond_init(&mut (*ptr).cond, &attr) != 0 {
+            abort()
+        }
+        assert(pthread_condattr_destroy(&mut attr) == 0);
+    }
+
     /// Unblock at least one thread blocked on the condition variable.
     pub unsafe fn signal(&mut self) {
         assert(pthread_cond_signal(&mut self.cond) == 0)
@@ -241,6 +350,33 @@ impl Cond {
     pub unsafe fn wait_guard(&mut self, guard: &mut LockGuard) {
         self.wait(guard.mutex)
     }
+
+    /// Like `wait`, but gives up and returns `true` if not notified within `ms` milliseconds.
+    /// Returns `false` if woken by a signal/broadcast (note that spurious wakeups may occur).
+    pub unsafe fn wait_timeout(&mut self, mutex: &mut Mutex, ms: u64) -> bool {
+        let mut now = uninit();
+        assert(clock_gettime(CLOCK_REALTIME, &mut now) == 0);
+        let mut nsec = now.tv_nsec + (ms % 1000) as i64 * 1_000_000;
+        let mut sec = now.tv_sec + (ms / 1000) as i64;
+        if nsec >= NSEC_PER_SEC {
+            nsec -= NSEC_PER_SEC;
+            sec += 1;
+        }
+        let deadline = timespec { tv_sec: sec, tv_nsec: nsec };
+        let rc = pthread_cond_timedwait(&mut self.cond, &mut mutex.mutex, &deadline);
+        if rc == ETIMEDOUT {
+            true
+        } else {
+            assert(rc == 0);
+            false
+        }
+    }
+
+    /// Like `wait_guard`, but gives up and returns `true` if not notified within `ms`
+    /// milliseconds.
+    pub unsafe fn wait_guard_timeout(&mut self, guard: &mut LockGuard, ms: u64) -> bool {
+        self.wait_timeout(guard.mutex, ms)
+    }
 }
 
 impl Drop for Cond {
@@ -265,6 +401,219 @@ impl<'a> Drop for LockGuard<'a> {
     }
 }
 
+/// A mutex that may be locked multiple times by the thread that already owns it, unlike `Mutex`
+/// which deadlocks in that case (or aborts, in a `debug` build where it is error-checking). Useful for building reentrant abstractions, e.g. a locked stdio
+/// handle whose `write_fmt` internally calls `write`.
+pub struct ReentrantMutex {
+    priv mutex: pthread_mutex_t
+}
+
+impl ReentrantMutex {
+    pub fn new() -> ReentrantMutex {
+        unsafe {
+            let mut attr = uninit();
+            if pthread_mutexattr_init(&mut attr) != 0 {
+                abort()
+            }
+            assert(pthread_mutexattr_settype(&mut attr, PTHREAD_MUTEX_RECURSIVE) == 0);
+            let mut mutex = uninit();
+            if pthread_mutex_init(&mut mutex, &attr) != 0 {
+                abort()
+            }
+            assert(pthread_mutexattr_destroy(&mut attr) == 0);
+            ReentrantMutex { mutex: mutex }
+        }
+    }
+
+    /// Grab ownership of the mutex. May be called again by the same thread without blocking.
+    pub unsafe fn lock(&mut self) {
+        assert(pthread_mutex_lock(&mut self.mutex) == 0)
+    }
+
+    /// Grab ownership of the mutex, returning a `ReentrantLockGuard` value releasing ownership of
+    /// the mutex in the destructor.
+    pub unsafe fn lock_guard<'a>(&'a mut self) -> ReentrantLockGuard<'a> {
+        self.lock();
+        ReentrantLockGuard { mutex: self }
+    }
+
+    /// Try to grab ownership of a lock, and return `true` if successful
+    pub unsafe fn trylock(&mut self) -> bool {
+        let rc = pthread_mutex_trylock(&mut self.mutex);
+        if rc == EBUSY {
+            false
+        } else {
+            assert(rc == 0);
+            true
+        }
+    }
+
+    /// Release ownership of the mutex.
+    pub unsafe fn unlock(&mut self) {
+        assert(pthread_mutex_unlock(&mut self.mutex) == 0)
+    }
+}
+
+impl Drop for ReentrantMutex {
+    fn drop(&mut self) {
+        unsafe {
+            assert(pthread_mutex_destroy(&mut self.mutex) == 0)
+        }
+    }
+}
+
+/// A scoped lock taking ownership of a `ReentrantMutex`
+pub struct ReentrantLockGuard<'a> {
+    priv mutex: &'a mut ReentrantMutex
+}
+
+#[unsafe_destructor]
+impl<'a> Drop for ReentrantLockGuard<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.mutex.unlock()
+        }
+    }
+}
+
+/// A reader-writer lock, allowing any number of concurrent readers or a single exclusive writer.
+pub struct RwLock {
+    priv rwlock: pthread_rwlock_t
+}
+
+impl RwLock {
+    pub fn new() -> RwLock {
+        unsafe {
+            let mut rwlock = uninit();
+            if pthread_rwlock_init(&mut rwlock, 0 as *pthread_rwlock_attr_t) != 0 {
+                abort()
+            }
+            RwLock { rwlock: rwlock }
+        }
+    }
+
+    /// Grab a shared read lock, blocking while a writer holds the lock.
+    pub unsafe fn read<'a>(&'a mut self) -> ReadGuard<'a> {
+        assert(pthread_rwlock_rdlock(&mut self.rwlock) == 0);
+        ReadGuard { rwlock: self }
+    }
+
+    /// Grab the exclusive write lock, blocking while any reader or writer holds the lock.
+    pub unsafe fn write<'a>(&'a mut self) -> WriteGuard<'a> {
+        assert(pthread_rwlock_wrlock(&mut self.rwlock) == 0);
+        WriteGuard { rwlock: self }
+    }
+
+    /// Try to grab a shared read lock, and return `true` if successful
+    pub unsafe fn try_read(&mut self) -> bool {
+        let rc = pthread_rwlock_tryrdlock(&mut self.rwlock);
+        if rc == EBUSY {
+            false
+        } else {
+            assert(rc == 0);
+            true
+        }
+    }
+
+    /// Try to grab the exclusive write lock, and return `true` if successful
+    pub unsafe fn try_write(&mut self) -> bool {
+        let rc = pthread_rwlock_trywrlock(&mut self.rwlock);
+        if rc == EBUSY {
+            false
+        } else {
+            assert(rc == 0);
+            true
+        }
+    }
+
+    /// Release ownership of the rwlock.
+    pub unsafe fn unlock(&mut self) {
+        assert(pthread_rwlock_unlock(&mut self.rwlock) == 0)
+    }
+}
+
+impl Drop for RwLock {
+    fn drop(&mut self) {
+        unsafe {
+            assert(pthread_rwlock_destroy(&mut self.rwlock) == 0)
+        }
+    }
+}
+
+/// A scoped shared lock taking a read lock on an `RwLock`
+pub struct ReadGuard<'a> {
+    priv rwlock: &'a mut RwLock
+}
+
+#[unsafe_destructor]
+impl<'a> Drop for ReadGuard<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.rwlock.unlock()
+        }
+    }
+}
+
+/// A scoped exclusive lock taking a write lock on an `RwLock`
+pub struct WriteGuard<'a> {
+    priv rwlock: &'a mut RwLock
+}
+
+#[unsafe_destructor]
+impl<'a> Drop for WriteGuard<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.rwlock.unlock()
+        }
+    }
+}
+
+#[no_freeze]
+struct FutureBox<A> {
+    result: Option<A>,
+    mutex: Mutex,
+    ready: Cond
+}
+
+/// A handle to the result of a task submitted to a `Pool`. `get` (and the destructor, if `get` is
+/// never called) blocks until the worker has finished computing the value.
+pub struct Future<A> {
+    priv ptr: Arc<FutureBox<A>>
+}
+
+impl<A: Send> Future<A> {
+    /// Block until the task finishes, then return the computed value.
+    pub fn get(self) -> A {
+        unsafe {
+            let value = {
+                let box: &mut FutureBox<A> = transmute(self.ptr.borrow());
+                let mut guard = box.mutex.lock_guard();
+                while box.result.is_none() {
+                    box.ready.wait_guard(&mut guard)
+                }
+                replace(&mut box.result, None).get()
+            };
+            // The value has already been retrieved, so suppress the blocking destructor (it
+            // would otherwise re-lock and wait forever, since nothing signals again).
+            forget(self);
+            value
+        }
+    }
+}
+
+#[unsafe_destructor]
+impl<A: Send> Drop for Future<A> {
+    fn drop(&mut self) {
+        unsafe {
+            let box: &mut FutureBox<A> = transmute(self.ptr.borrow());
+            let mut guard = box.mutex.lock_guard();
+            while box.result.is_none() {
+                box.ready.wait_guard(&mut guard)
+            }
+        }
+    }
+}
+
 /// A pool of worker threads
 pub struct Pool {
     priv queue: Queue<Option<proc()>>,
@@ -297,6 +646,26 @@ impl Pool {
     pub fn submit(&self, task: proc()) {
         self.queue.push(Some(task))
     }
+
+    /// Submit a task that produces a value, returning a `Future` that can be used to retrieve it
+    /// once a worker has run the task to completion. Useful for map/reduce style fan-out, where
+    /// `submit` alone would have no way to hand the result back to the caller.
+    pub fn submit_async<A: Send>(&self, task: proc() -> A) -> Future<A> {
+        unsafe {
+            let box = FutureBox { result: None, mutex: Mutex::new(), ready: Cond::new() };
+            let future = Future { ptr: Arc::new_unchecked(box) };
+            let put_future = future.ptr.clone();
+            self.queue.push(Some(proc() {
+                let value = task();
+                let box: &mut FutureBox<A> = transmute(put_future.borrow());
+                box.mutex.lock();
+                box.result = Some(value);
+                box.mutex.unlock();
+                box.ready.signal()
+            }));
+            future
+        }
+    }
 }
 
 impl Drop for Pool {